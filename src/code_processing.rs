@@ -1,14 +1,21 @@
 use darklua_core::{Configuration, GeneratorParameters, Resources, Options};
 use stacker::maybe_grow;
-use std::{path::PathBuf, error::Error};
+use std::{path::{Path, PathBuf}, error::Error};
 use tokio::time::Instant;
-use regex::Regex;
 use colored::Colorize;
-use crate::file_processing::{write_in_chunks, read_file};
-use crate::require_parser::{parse_file, remove_comments, IN_STRING_PATTERN};
+use crate::dependency_graph::DependencyGraph;
+use crate::file_processing::write_in_chunks;
+use crate::source_map::{SourceMap, SourceRange};
+
+// `loaded` tracks which modules have run separately from `__cache`'s values,
+// since assigning `nil` into a Lua table removes the key — a module whose
+// body returns nothing (the normal idiom for a side-effect-only module)
+// would otherwise be indistinguishable from "never loaded" and re-execute
+// its side effects on every require.
+const REQUIRE_FN: &str = "local __loaded = {}\nlocal function __require(name, ...)\n    if __loaded[name] then\n        return __cache[name]\n    end\n\n    local result = __modules[name](...)\n    __loaded[name] = true\n    __cache[name] = result\n    return result\nend\n\n";
 
 // Processes the code in the given buffer
-fn process_code(buffer: PathBuf, minify: bool) {
+pub(crate) fn process_code(buffer: PathBuf, minify: bool) {
     // Initialize the resources and parameters
     let resources = Resources::from_file_system();
     let generator_parameters = if minify {
@@ -27,69 +34,146 @@ fn process_code(buffer: PathBuf, minify: bool) {
     });
 }
 
+// Appends `text` to `output`, advancing `line` by the number of newlines it contains.
+fn push(output: &mut String, line: &mut usize, text: &str) {
+    *line += text.matches('\n').count();
+    output.push_str(text);
+}
 
-// Replaces all require calls in a file with the contents of the file at the given path
-async fn replace_requires(origin: &str, requires: Vec<(String, String, String, String)>) -> Result<String, Box<dyn Error>> {
-    let origin_buf = PathBuf::from(origin);
-    let main_buf = PathBuf::from(origin_buf.parent().unwrap().to_str().unwrap());
-    let mut replaced_contents = remove_comments(&read_file(&origin_buf).await?).await?;  // Initialize with the original content
+// Emits the `__modules`/`__cache`/`__require` preamble, one
+// `__modules["<path>"] = function(...) ... end` entry per dependency (in the
+// graph's reverse-topological order, so every module is defined before the
+// first site that requires it), followed by the entry point's own body.
+//
+// Alongside the bundled text, tracks the output line range each module body
+// occupies so the caller can write a companion source map. When `minify` is
+// false, a `-- <source_path>` banner is emitted right before each module so
+// stack traces from the wrapped module functions stay readable on their own.
+pub(crate) fn emit_bundle(graph: &DependencyGraph, minify: bool) -> (String, SourceMap) {
+    let (entry, dependencies) = graph
+        .order()
+        .split_last()
+        .expect("a built graph always contains at least its entry module");
+
+    let mut output = String::from("local __modules, __cache = {}, {}\n\n");
+    let mut line = output.matches('\n').count() + 1;
+    let mut ranges = Vec::new();
+
+    // `__require` must be declared (and thus captured as an upvalue) before any
+    // `__modules[...] = function(...) ... end` entry that calls it, since a
+    // `local` declared later in the chunk isn't visible inside function
+    // literals written earlier in the same chunk.
+    push(&mut output, &mut line, REQUIRE_FN);
+
+    for path in dependencies {
+        if !minify {
+            push(&mut output, &mut line, &format!("-- {}\n", path));
+        }
 
-    for (mut matched, require, args, func_args) in requires {
-        let require_path = main_buf.join(&require);
-        let contents = read_file(&require_path).await?;
+        push(&mut output, &mut line, &format!("__modules[\"{}\"] = function(...)\n", path));
 
-        // Check if the first and last characters are either ' or "
-        let in_string_regex = Regex::new(IN_STRING_PATTERN)?;
-        if in_string_regex.is_match(&matched) {
-            // Replace the first and last characters with [[ and ]]
-            let replaced = format!("[[{}]]", &matched[1..matched.len() - 1]);
-            replaced_contents = replaced_contents.replace(&matched, &replaced);
+        let body_start = line;
+        push(&mut output, &mut line, graph.source(path).unwrap());
+        let body_end = line;
 
-            // Remove the first and last string in matched
-            matched.remove(0);
-            matched.pop();
-        }
+        push(&mut output, &mut line, "\nend\n\n");
 
-        // Wrap the contents in a function call with the require arguments as parameters
-        let mut replaced = format!("(function(...)\n\t{}\nend)({});", contents, args);
+        ranges.push(SourceRange {
+            output_start: body_start,
+            output_end: body_end,
+            source_path: path.clone(),
+            breakpoints: graph.line_map(path).to_vec(),
+        });
+    }
 
-        if !func_args.is_empty() {
-            // Remove the last semicolon and add func_args
-            replaced.pop();
-            replaced.push_str(&func_args);
-        }
+    if !minify {
+        push(&mut output, &mut line, &format!("-- {}\n", entry));
+    }
 
-        // If the require call was multiline, indent the contents of the required file
-        if matched.contains("\n") {
-            replaced = replaced.lines().map(|line| format!("    {}", line)).collect::<Vec<String>>().join("\n");
-        }
+    let entry_start = line;
+    push(&mut output, &mut line, graph.source(entry).unwrap());
+    let entry_end = line;
 
-        // Replace the matched require statement with the contents and accumulate in the result
-        replaced_contents = replaced_contents.replace(&matched, &replaced);
-    }
+    ranges.push(SourceRange {
+        output_start: entry_start,
+        output_end: entry_end,
+        source_path: entry.clone(),
+        breakpoints: graph.line_map(entry).to_vec(),
+    });
+
+    (output, SourceMap::new(ranges))
+}
 
-    Ok(replaced_contents)
+/// Resolves a bundled output line back to `(source_path, original_line)` using
+/// the map written alongside `bundle_path` by [`bundle`].
+pub fn resolve_line(bundle_path: &str, output_line: usize) -> Result<Option<(String, usize)>, Box<dyn Error>> {
+    let map = SourceMap::load(Path::new(&format!("{}.map", bundle_path)))?;
+    Ok(map.resolve(output_line).map(|(path, line)| (path.to_string(), line)))
 }
 
 // Bundles the given file and writes the bundled code to the output file
-pub async fn bundle(main_path: &str, bundle_path: &str, _minify: bool, noprocess: bool) -> Result<(), Box<dyn Error>> {
+pub async fn bundle(main_path: &str, bundle_path: &str, minify: bool, noprocess: bool) -> Result<(), Box<dyn Error>> {
     let start = Instant::now();
 
-    // Parse the main file for require calls and replace them with the contents of the required files
-    let calls = parse_file(main_path).await?;
-    let bundled = replace_requires(main_path, calls).await?;
+    // Build the dependency graph and emit modules in dependency-first order
+    let graph = DependencyGraph::build(Path::new(main_path)).await?;
+    let (bundled, source_map) = emit_bundle(&graph, minify);
 
-    // Write the bundled code to the output file
+    // Write the bundled code and its companion source map
     write_in_chunks(bundle_path, bundled.as_bytes(), 1024 * 1024).await?;
+    source_map.write(Path::new(&format!("{}.map", bundle_path)))?;
 
     println!("{}", format!("{} {} {}", "Bundled".blue(), main_path, format!("in {:?}", start.elapsed()).dimmed()));
 
     // Process the bundled code if the -n flag is not present
     if !noprocess {
         let start = Instant::now();
-        process_code(PathBuf::from(bundle_path), _minify);
+        process_code(PathBuf::from(bundle_path), minify);
         println!("{}", format!("{} {} {}", "Processed".blue(), bundle_path, format!("in {:?}", start.elapsed()).dimmed()));
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dependency_graph::DependencyGraph;
+    use crate::test_support::{scratch_dir, write};
+
+    // Regression test for the bug a real Lua VM would hit immediately: if
+    // `__require` is emitted after the `__modules[...]` entries that
+    // reference it, those entries capture it as a global (nil) instead of
+    // the later local, and every multi-level require chain breaks.
+    #[tokio::test]
+    async fn require_fn_is_emitted_before_any_module_entry() {
+        let dir = scratch_dir("require-fn-order");
+        write(&dir, "b.lua", "return 1");
+        let entry = write(&dir, "a.lua", "return require(\"b.lua\")");
+
+        let graph = DependencyGraph::build(&entry).await.unwrap();
+        let (bundled, _) = emit_bundle(&graph, false);
+
+        let require_fn_pos = bundled.find("local function __require").unwrap();
+        let first_module_pos = bundled.find("__modules[").unwrap();
+        assert!(require_fn_pos < first_module_pos, "__require must be declared before any __modules[...] entry references it");
+    }
+
+    // Regression test for the nil-caching bug: assigning `nil` into a Lua
+    // table removes the key, so gating re-execution on `__cache[name] ~= nil`
+    // can't distinguish "never loaded" from "loaded, returned nothing" — the
+    // normal idiom for a side-effect-only module. `__require` must gate on a
+    // separate loaded-flag instead.
+    #[tokio::test]
+    async fn require_fn_gates_on_loaded_flag_not_cached_value() {
+        let dir = scratch_dir("nil-return-module");
+        write(&dir, "b.lua", "return nil");
+        let entry = write(&dir, "a.lua", "return require(\"b.lua\")");
+
+        let graph = DependencyGraph::build(&entry).await.unwrap();
+        let (bundled, _) = emit_bundle(&graph, false);
+
+        assert!(bundled.contains("__loaded[name]"), "require must track loadedness separately from the cached value");
+        assert!(!bundled.contains("__cache[name] ~= nil"), "a nil-returning module would be re-executed on every require under this check");
+    }
+}
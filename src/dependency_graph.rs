@@ -0,0 +1,330 @@
+use async_recursion::async_recursion;
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::diagnostics::{position_of, Diagnostic};
+use crate::file_processing::read_file;
+use crate::require_parser::{parse_file, RequireCall};
+
+/// A require cycle, e.g. `a.lua -> b.lua -> a.lua`.
+#[derive(Debug)]
+pub struct CircularRequireError {
+    pub cycle: Vec<String>,
+}
+
+impl fmt::Display for CircularRequireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "circular require: {}", self.cycle.join(" -> "))
+    }
+}
+
+impl Error for CircularRequireError {}
+
+// DFS coloring: White hasn't been visited, Gray is on the current path (an
+// ancestor), Black is fully resolved. A Gray node re-encountered on the stack
+// is a back-edge, i.e. a require cycle.
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum Color {
+    White,
+    Gray,
+    Black,
+}
+
+/// The module graph of an entry point's require tree, keyed by canonical path.
+///
+/// `order` lists every module in reverse-topological order (dependencies
+/// before dependents), so emitting modules in this order guarantees each one
+/// is defined before the first site that requires it.
+pub struct DependencyGraph {
+    order: Vec<String>,
+    sources: HashMap<String, String>,
+    requires: HashMap<String, Vec<String>>,
+    line_maps: HashMap<String, Vec<(usize, usize)>>,
+}
+
+impl DependencyGraph {
+    /// Builds the dependency graph rooted at `entry`, reading and rewriting
+    /// every reachable module exactly once even under diamond dependencies.
+    pub async fn build(entry: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut colors = HashMap::new();
+        let mut sources = HashMap::new();
+        let mut requires = HashMap::new();
+        let mut order = Vec::new();
+        let mut stack = Vec::new();
+        let mut line_maps = HashMap::new();
+
+        visit(entry, &mut colors, &mut sources, &mut requires, &mut order, &mut stack, &mut line_maps).await?;
+
+        Ok(Self { order, sources, requires, line_maps })
+    }
+
+    /// Every module in the graph, in reverse-topological (dependency-first) order.
+    pub fn order(&self) -> &[String] {
+        &self.order
+    }
+
+    /// The rewritten source of the module at `path` (its require calls already
+    /// replaced with `__require(...)` calls).
+    pub fn source(&self, path: &str) -> Option<&str> {
+        self.sources.get(path).map(String::as_str)
+    }
+
+    /// The canonical paths `path` requires, in the order they appear in its source.
+    pub fn requires(&self, path: &str) -> Option<&[String]> {
+        self.requires.get(path).map(Vec::as_slice)
+    }
+
+    /// Breakpoints mapping `path`'s rewritten-source line numbers back to its
+    /// original line numbers, as `(rewritten_line, offset)` pairs sorted
+    /// ascending by `rewritten_line`. Needed because splicing a multi-line
+    /// require call into a single-line `__require(...)` call shifts every
+    /// later line up, so a flat 1:1 line mapping would be wrong past the
+    /// first such splice.
+    pub fn line_map(&self, path: &str) -> &[(usize, usize)] {
+        self.line_maps.get(path).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Replaces `path`'s rewritten source in-place without re-running the DFS.
+    /// Only valid when `path`'s set of requires hasn't changed, since the
+    /// `__require(...)` rewrites embedded in `source` still point at the same
+    /// canonical keys.
+    pub fn patch_source(&mut self, path: &str, source: String) {
+        self.sources.insert(path.to_string(), source);
+    }
+
+    /// Replaces `path`'s line breakpoints in-place. Callers that patch a
+    /// module's source outside the DFS (e.g. the watch fast path) must also
+    /// patch this, since an edit can shift a module's internal line numbers
+    /// even when its require set is unchanged.
+    pub fn patch_line_map(&mut self, path: &str, breakpoints: Vec<(usize, usize)>) {
+        self.line_maps.insert(path.to_string(), breakpoints);
+    }
+}
+
+/// Computes the line breakpoints for a module whose `calls` (in source
+/// order, as found in its pristine `contents`) are about to be spliced down
+/// to single-line `__require(...)` calls. Shared by the DFS build path and
+/// the watch fast-patch path so both keep a module's source map accurate.
+pub(crate) fn compute_line_breakpoints(contents: &str, calls: &[RequireCall]) -> Vec<(usize, usize)> {
+    let mut breakpoints = Vec::new();
+    let mut cumulative = 0usize;
+
+    for call in calls {
+        let delta = contents[call.span.clone()].matches('\n').count();
+        if delta == 0 {
+            continue;
+        }
+
+        let (call_line, _) = position_of(contents, call.span.start);
+        cumulative += delta;
+        breakpoints.push((call_line - (cumulative - delta) + 1, cumulative));
+    }
+
+    breakpoints
+}
+
+/// Canonicalizes a module path so the same file keys identically no matter
+/// how many require call sites point at it.
+pub fn canonical_path(path: &Path) -> String {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf()).to_string_lossy().into_owned()
+}
+
+fn is_glob(target: &str) -> bool {
+    target.contains(['*', '?', '['])
+}
+
+/// Expands a glob `pattern` relative to the file that required it (`origin`)
+/// into the lexicographically sorted set of matching files, so output stays
+/// reproducible across filesystems.
+fn expand_glob(origin: &Path, pattern: &str) -> Result<Vec<PathBuf>, String> {
+    let base = origin.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+    let joined = base.join(pattern);
+    let joined = joined.to_str().ok_or_else(|| format!("invalid glob pattern: {}", pattern))?;
+
+    let mut matches = glob::glob(joined)
+        .map_err(|err| err.to_string())?
+        .filter_map(Result::ok)
+        .collect::<Vec<_>>();
+
+    matches.sort();
+    Ok(matches)
+}
+
+/// Keys a glob match by its path relative to the glob's base directory, minus
+/// its extension (e.g. `components/button`), rather than its bare file stem, so two matches
+/// in different directories that happen to share a filename don't collide
+/// and silently overwrite one another in the generated module table.
+fn glob_entry_key(base: &Path, matched: &Path) -> String {
+    matched
+        .strip_prefix(base)
+        .unwrap_or(matched)
+        .with_extension("")
+        .to_string_lossy()
+        .replace(std::path::MAIN_SEPARATOR, "/")
+}
+
+#[async_recursion]
+async fn visit(
+    path: &Path,
+    colors: &mut HashMap<String, Color>,
+    sources: &mut HashMap<String, String>,
+    requires: &mut HashMap<String, Vec<String>>,
+    order: &mut Vec<String>,
+    stack: &mut Vec<String>,
+    line_maps: &mut HashMap<String, Vec<(usize, usize)>>,
+) -> Result<String, Box<dyn Error>> {
+    let key = canonical_path(path);
+
+    match colors.get(&key) {
+        Some(Color::Black) => return Ok(key),
+        Some(Color::Gray) => {
+            let cycle_start = stack.iter().position(|entry| *entry == key).unwrap_or(0);
+            let mut cycle = stack[cycle_start..].to_vec();
+            cycle.push(key);
+            return Err(Box::new(CircularRequireError { cycle }));
+        }
+        _ => {}
+    }
+
+    colors.insert(key.clone(), Color::Gray);
+    stack.push(key.clone());
+
+    let contents = read_file(&path.to_path_buf()).await?;
+    let (calls, malformed) = parse_file(&contents)?;
+
+    if let Some(bad_call) = malformed.into_iter().next() {
+        return Err(Box::new(Diagnostic::new(
+            path.display().to_string(),
+            &contents,
+            bad_call.span,
+            "malformed require: expected a single string literal argument",
+        )));
+    }
+
+    // Splicing a multi-line `require(...)` call down to a single-line
+    // `__require(...)` call shrinks the line count from that point on, so
+    // record where each collapse happens (in source order, before any
+    // splicing) and how many lines it removes.
+    line_maps.insert(key.clone(), compute_line_breakpoints(&contents, &calls));
+
+    let mut rewritten = contents;
+    let mut dependencies = Vec::new();
+
+    // Splice from the last call to the first so earlier spans stay valid as later ones are replaced.
+    for call in calls.into_iter().rev() {
+        let replacement = if is_glob(&call.path) {
+            let matches = expand_glob(path, &call.path).map_err(|err| {
+                Box::new(Diagnostic::new(path.display().to_string(), &rewritten, call.span.clone(), err)) as Box<dyn Error>
+            })?;
+
+            if matches.is_empty() {
+                return Err(Box::new(Diagnostic::new(
+                    path.display().to_string(),
+                    &rewritten,
+                    call.span,
+                    format!("glob matched no files: {}", call.path),
+                )));
+            }
+
+            let base = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+            let mut entries = Vec::new();
+            let mut glob_dependencies = Vec::new();
+            for matched in matches {
+                let dep_key = visit(&matched, colors, sources, requires, order, stack, line_maps).await?;
+                let name = glob_entry_key(&base, &matched);
+                entries.push(format!("[\"{}\"] = __require(\"{}\")", name, dep_key));
+                glob_dependencies.push(dep_key);
+            }
+            // Pushed in reverse so the final `dependencies.reverse()` leaves them in sorted order.
+            dependencies.extend(glob_dependencies.into_iter().rev());
+
+            format!("{{ {} }}", entries.join(", "))
+        } else {
+            let require_path = path.parent().unwrap_or_else(|| Path::new("")).join(&call.path);
+
+            if !require_path.exists() {
+                return Err(Box::new(Diagnostic::new(
+                    path.display().to_string(),
+                    &rewritten,
+                    call.span,
+                    format!("file not found: {}", require_path.display()),
+                )));
+            }
+
+            let dep_key = visit(&require_path, colors, sources, requires, order, stack, line_maps).await?;
+            dependencies.push(dep_key.clone());
+
+            if call.extra_args.is_empty() {
+                format!("__require(\"{}\")", dep_key)
+            } else {
+                format!("__require(\"{}\", {})", dep_key, call.extra_args)
+            }
+        };
+
+        rewritten.replace_range(call.span, &replacement);
+    }
+
+    dependencies.reverse(); // restore source order, since calls were processed last-to-first
+
+    stack.pop();
+    colors.insert(key.clone(), Color::Black);
+    sources.insert(key.clone(), rewritten);
+    requires.insert(key.clone(), dependencies);
+    order.push(key.clone());
+
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{scratch_dir, write};
+
+    #[tokio::test]
+    async fn build_orders_dependencies_before_dependents() {
+        let dir = scratch_dir("topo");
+        write(&dir, "c.lua", "return 3");
+        write(&dir, "b.lua", "return require(\"c.lua\") + 1");
+        let entry = write(&dir, "a.lua", "return require(\"b.lua\") + 1");
+
+        let graph = DependencyGraph::build(&entry).await.unwrap();
+        let order = graph.order();
+
+        let a_index = order.iter().position(|p| p.ends_with("a.lua")).unwrap();
+        let b_index = order.iter().position(|p| p.ends_with("b.lua")).unwrap();
+        let c_index = order.iter().position(|p| p.ends_with("c.lua")).unwrap();
+
+        assert!(c_index < b_index, "c.lua must be emitted before b.lua");
+        assert!(b_index < a_index, "b.lua must be emitted before a.lua");
+    }
+
+    #[tokio::test]
+    async fn build_detects_circular_requires() {
+        let dir = scratch_dir("cycle");
+        write(&dir, "b.lua", "return require(\"a.lua\")");
+        let entry = write(&dir, "a.lua", "return require(\"b.lua\")");
+
+        let err = DependencyGraph::build(&entry).await.unwrap_err();
+        assert!(err.to_string().contains("circular require"));
+    }
+
+    #[tokio::test]
+    async fn glob_requires_key_same_named_files_in_different_directories_distinctly() {
+        let dir = scratch_dir("glob-collision");
+        std::fs::create_dir_all(dir.join("red")).unwrap();
+        std::fs::create_dir_all(dir.join("blue")).unwrap();
+        write(&dir.join("red"), "button.lua", "return \"red\"");
+        write(&dir.join("blue"), "button.lua", "return \"blue\"");
+        let entry = write(&dir, "a.lua", "return require(\"*/*.lua\")");
+
+        let graph = DependencyGraph::build(&entry).await.unwrap();
+        let source = graph.source(&canonical_path(&entry)).unwrap();
+
+        assert!(source.contains("[\"red/button\"]"));
+        assert!(source.contains("[\"blue/button\"]"));
+    }
+}
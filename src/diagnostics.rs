@@ -0,0 +1,94 @@
+use std::{error::Error, fmt, ops::Range};
+
+/// A source-anchored error: a byte span inside a specific file, rendered with
+/// the offending line and a caret underline so a bad `require` can be traced
+/// straight back to the call that caused it instead of surfacing as an opaque
+/// `Box<dyn Error>` message or a panic.
+#[derive(Debug)]
+pub struct Diagnostic {
+    file: String,
+    message: String,
+    line: usize,
+    column: usize,
+    line_text: String,
+    underline_len: usize,
+}
+
+impl Diagnostic {
+    /// Builds a diagnostic for `span` (a byte range within `source`) inside `file`.
+    pub fn new(file: impl Into<String>, source: &str, span: Range<usize>, message: impl Into<String>) -> Self {
+        let (line, column) = position_of(source, span.start);
+        let line_text = source.lines().nth(line - 1).unwrap_or("").to_string();
+        let underline_len = span.len().max(1);
+
+        Self {
+            file: file.into(),
+            message: message.into(),
+            line,
+            column,
+            line_text,
+            underline_len,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let gutter = self.line.to_string();
+        let pad = " ".repeat(gutter.len());
+
+        writeln!(f, "{}: {}", self.file, self.message)?;
+        writeln!(f, "{} --> {}:{}:{}", pad, self.file, self.line, self.column)?;
+        writeln!(f, "{} |", pad)?;
+        writeln!(f, "{} | {}", gutter, self.line_text)?;
+        write!(f, "{} | {}{}", pad, " ".repeat(self.column.saturating_sub(1)), "^".repeat(self.underline_len))
+    }
+}
+
+impl Error for Diagnostic {}
+
+// Converts a byte offset into a 1-based (line, column) pair.
+pub(crate) fn position_of(source: &str, byte_offset: usize) -> (usize, usize) {
+    let mut line = 1;
+    let mut line_start = 0;
+
+    for (index, byte) in source.as_bytes().iter().enumerate().take(byte_offset) {
+        if *byte == b'\n' {
+            line += 1;
+            line_start = index + 1;
+        }
+    }
+
+    (line, byte_offset - line_start + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn position_of_finds_line_and_column() {
+        let source = "local a = 1\nlocal b = require(x)\n";
+        let offset = source.find("require").unwrap();
+        assert_eq!(position_of(source, offset), (2, 11));
+    }
+
+    #[test]
+    fn position_of_handles_the_first_line() {
+        assert_eq!(position_of("require(x)", 0), (1, 1));
+    }
+
+    #[test]
+    fn display_underlines_the_span_on_its_own_line() {
+        let source = "local a = 1\nrequire(x)\n";
+        let span_start = source.find("require(x)").unwrap();
+        let span = span_start..span_start + "require(x)".len();
+
+        let diagnostic = Diagnostic::new("foo.lua", source, span, "malformed require");
+        let rendered = diagnostic.to_string();
+
+        assert!(rendered.contains("foo.lua:2:1"));
+        assert!(rendered.contains("require(x)"));
+        assert!(rendered.contains(&"^".repeat("require(x)".len())));
+    }
+}
@@ -0,0 +1,9 @@
+pub mod code_processing;
+pub mod dependency_graph;
+pub mod diagnostics;
+pub mod file_processing;
+pub mod require_parser;
+pub mod source_map;
+#[cfg(test)]
+pub(crate) mod test_support;
+pub mod watch;
@@ -1,11 +1,148 @@
 use luabundler::code_processing::bundle;
-use std::env;
+use luabundler::watch::watch;
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process,
+};
+
+struct Config {
+    entries: Vec<String>,
+    output: Option<String>,
+    minify: bool,
+    no_process: bool,
+    watch: bool,
+}
+
+const USAGE: &str = "usage: luabundler [-m|--minify] [-n|--no-process] [-w|--watch] [-o|--output <path>] <entry>...";
+
+fn parse_args(args: &[String]) -> Result<Config, String> {
+    let mut entries = Vec::new();
+    let mut output = None;
+    let mut minify = false;
+    let mut no_process = false;
+    let mut watch = false;
+
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-m" | "--minify" => minify = true,
+            "-n" | "--no-process" => no_process = true,
+            "-w" | "--watch" => watch = true,
+            "-o" | "--output" => output = Some(iter.next().ok_or("--output requires a path")?.clone()),
+            entry => entries.push(entry.to_string()),
+        }
+    }
+
+    if entries.is_empty() {
+        return Err(USAGE.to_string());
+    }
+
+    Ok(Config { entries, output, minify, no_process, watch })
+}
+
+// Expands a (possibly globbed) entry argument into the lexicographically sorted set of matching files.
+fn expand_entry(pattern: &str) -> Result<Vec<PathBuf>, String> {
+    if !pattern.contains(['*', '?', '[']) {
+        return Ok(vec![PathBuf::from(pattern)]);
+    }
+
+    let mut matches = glob::glob(pattern).map_err(|err| err.to_string())?.filter_map(Result::ok).collect::<Vec<_>>();
+    matches.sort();
+
+    if matches.is_empty() {
+        return Err(format!("glob matched no files: {}", pattern));
+    }
+
+    Ok(matches)
+}
+
+// Derives a default output path for an entry when `--output` wasn't given.
+fn default_output(entry: &Path) -> PathBuf {
+    let stem = entry.file_stem().and_then(|s| s.to_str()).unwrap_or("bundle");
+    let extension = entry.extension().and_then(|e| e.to_str()).unwrap_or("lua");
+    entry.with_file_name(format!("{}.bundled.{}", stem, extension))
+}
+
+// When multiple entries are being bundled in one run, `--output` names a
+// directory each entry is written into; otherwise it names the output file directly.
+fn resolve_output(entry: &Path, output: Option<&str>, multiple: bool) -> PathBuf {
+    match output {
+        Some(out) if multiple => Path::new(out).join(entry.file_name().unwrap_or_default()),
+        Some(out) => PathBuf::from(out),
+        None => default_output(entry),
+    }
+}
 
 #[tokio::main]
 async fn main() {
     let args: Vec<String> = env::args().collect();
-    let minify = args.contains(&String::from("-m")) || args.contains(&String::from("--minify"));
-    let no_process = args.contains(&String::from("-n")) || args.contains(&String::from("--no-process"));
 
-    bundle("tests/test.lua", "tests/bundled.lua", minify, no_process).await.unwrap();
-}
\ No newline at end of file
+    let config = parse_args(&args).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    let mut entries = Vec::new();
+    for pattern in &config.entries {
+        match expand_entry(pattern) {
+            Ok(matches) => entries.extend(matches),
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+    }
+    entries.sort();
+
+    let multiple = entries.len() > 1;
+
+    for entry in &entries {
+        let entry_path = entry.to_string_lossy().into_owned();
+        let bundle_path = resolve_output(entry, config.output.as_deref(), multiple).to_string_lossy().into_owned();
+
+        let result = if config.watch {
+            watch(&entry_path, &bundle_path, config.minify, config.no_process).await
+        } else {
+            bundle(&entry_path, &bundle_path, config.minify, config.no_process).await
+        };
+
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_entry_passes_through_a_non_glob_path_unchanged() {
+        let matches = expand_entry("main.lua").unwrap();
+        assert_eq!(matches, vec![PathBuf::from("main.lua")]);
+    }
+
+    #[test]
+    fn expand_entry_errors_when_a_glob_matches_no_files() {
+        let err = expand_entry("no-such-directory-xyz/*.lua").unwrap_err();
+        assert!(err.contains("glob matched no files"));
+    }
+
+    #[test]
+    fn default_output_inserts_bundled_before_the_extension() {
+        assert_eq!(default_output(Path::new("src/main.lua")), PathBuf::from("src/main.bundled.lua"));
+    }
+
+    #[test]
+    fn resolve_output_joins_output_dir_only_for_multiple_entries() {
+        assert_eq!(resolve_output(Path::new("a/main.lua"), Some("dist"), true), PathBuf::from("dist/main.lua"));
+        assert_eq!(resolve_output(Path::new("a/main.lua"), Some("dist/out.lua"), false), PathBuf::from("dist/out.lua"));
+    }
+
+    #[test]
+    fn parse_args_requires_at_least_one_entry() {
+        assert!(parse_args(&["luabundler".to_string()]).is_err());
+    }
+}
@@ -1,68 +1,172 @@
-use async_recursion::async_recursion;
-use regex::Regex;
+use full_moon::ast::{Call, Expression, FunctionArgs, FunctionCall, Prefix, Suffix, Value};
+use full_moon::node::Node;
+use full_moon::visitors::Visitor;
 use std::error::Error;
-use std::path::PathBuf;
-use tokio::fs::read_to_string;
-
-const REQUIRE_PATTERNS: &[&str] = &[
-    // require("module.lua",...) : 'require("module.lua",...)'
-    r#"['"]?require\s*\(\\*['"](.*?)\\*['"]\s*(?:,\s*(.*?))?\)\s*;?\s*([.(].*)?['"]?"#,
-
-    // require"module.lua" : 'require"module.lua"'
-    r#"['"]?require\s*\\*['"](.*?)\\*['"]\s*;?['"]?"#,
-];
-
-// Matches strings: "string", 'string'
-pub const IN_STRING_PATTERN: &str = r#"^['"](.+)['"]$"#;
-
-// Matches comments: --, --[[ ]], --[=[ ]=]
-const IN_COMMENT_PATTERN: &str = r#"--\[=*\[[\s\S]*?\]=*\]|['"]*--\s*.*['"]?"#;
-
-// Removes comments from a file
-pub async fn remove_comments(contents: &str) -> Result<String, Box<dyn Error>> {
-    // Create a regex instance for the comment pattern
-    let re: Regex = Regex::new(IN_COMMENT_PATTERN)?;
-    let mut cleaned_contents = String::from(contents);
-
-    // Replace all matched comments with an empty string
-    for cap in re.captures_iter(contents) {
-        // check if the comment is in a string, if so, don't remove it
-        let matched = cap.get(0).unwrap().as_str().trim().to_string();
-        let in_string_regex = Regex::new(IN_STRING_PATTERN)?;
-        if !in_string_regex.is_match(&matched) {
-            cleaned_contents = cleaned_contents.replace(&matched, "");
-        }
-    }
+use std::ops::Range;
+
+/// A single `require(...)` call found while walking a file's syntax tree.
+///
+/// `span` covers only the `require(...)` call itself (not any suffix chained
+/// onto it, e.g. the `.run()` in `require("x").run()`), so callers can splice
+/// the original source by byte offset instead of matching on text.
+#[derive(Debug, Clone)]
+pub struct RequireCall {
+    /// The literal path passed as the first argument, e.g. `require("foo.lua")` -> `foo.lua`.
+    pub path: String,
+    /// Source text of any arguments after the path, e.g. `require("foo.lua", a, b)` -> `a, b`.
+    pub extra_args: String,
+    /// Byte range of the `require(...)` call within the file's source.
+    pub span: Range<usize>,
+}
+
+/// A call shaped like `require(...)` whose argument isn't a single string
+/// literal (e.g. `require()`, `require(x)`, `require("a" .. "b")`), so it
+/// can't be resolved to a module path.
+#[derive(Debug, Clone)]
+pub struct MalformedRequire {
+    pub span: Range<usize>,
+}
 
-    Ok(cleaned_contents.into())
+#[derive(Default)]
+struct RequireVisitor {
+    calls: Vec<RequireCall>,
+    malformed: Vec<MalformedRequire>,
 }
 
-// Recursively parses a file for require calls, and returns a vector of (require, args) tuples
-#[async_recursion]
-pub async fn parse_file(path: &str) -> Result<Vec<(String, String, String, String)>, Box<dyn Error>> {
-    let mut require_path = PathBuf::from(path);
-    let contents = remove_comments(&read_to_string(require_path.clone()).await?).await?;
-    let mut calls = Vec::new();
-
-    for pattern in REQUIRE_PATTERNS {
-        let regex = Regex::new(pattern)?;
-        for cap in regex.captures_iter(&contents) {
-            let matched = cap.get(0).unwrap().as_str().trim().to_string();
-            let require = cap.get(1).unwrap().as_str().trim().to_string();
-            let args = cap.get(2).map_or(String::new(), |m| m.as_str().trim().to_string());
-            let func_args = cap.get(3).map_or(String::new(), |m| m.as_str().trim().to_string());
-
-            require_path.pop();
-            require_path.push(&require);
-
-            calls.push((matched, require, args, func_args));
-
-            // Recursively parse the require file and append the results to the vector
-            if require_path.exists() {
-                calls.append(&mut parse_file(require_path.to_str().unwrap()).await?);
+impl Visitor for RequireVisitor {
+    fn visit_function_call(&mut self, call: &FunctionCall) {
+        let Prefix::Name(name) = call.prefix() else {
+            return;
+        };
+
+        if name.token().to_string() != "require" {
+            return;
+        }
+
+        let Some(Suffix::Call(Call::AnonymousCall(args))) = call.suffixes().next() else {
+            return;
+        };
+
+        let Some(start) = call.start_position() else {
+            return;
+        };
+
+        match args {
+            // `require "foo.lua"` / `require'foo.lua'`
+            FunctionArgs::String(token) => {
+                let Some(end) = token.end_position() else {
+                    return;
+                };
+
+                self.calls.push(RequireCall {
+                    path: unquote(&token.token().to_string()),
+                    extra_args: String::new(),
+                    span: start.bytes()..end.bytes(),
+                });
             }
+            // `require(...)`
+            FunctionArgs::Parentheses { parentheses, arguments } => {
+                let Some(end) = parentheses.tokens().1.end_position() else {
+                    return;
+                };
+
+                let span = start.bytes()..end.bytes();
+                let mut arguments = arguments.iter();
+
+                match arguments.next() {
+                    Some(Expression::Value { value, .. }) if matches!(value.as_ref(), Value::String(_)) => {
+                        let Value::String(literal) = value.as_ref() else {
+                            unreachable!()
+                        };
+
+                        self.calls.push(RequireCall {
+                            path: unquote(&literal.token().to_string()),
+                            extra_args: arguments.map(Expression::to_string).collect::<Vec<_>>().join(", "),
+                            span,
+                        });
+                    }
+                    _ => self.malformed.push(MalformedRequire { span }),
+                }
+            }
+            _ => {}
         }
     }
+}
+
+/// Strips the surrounding `'...'` or `"..."` from a string token's raw text.
+fn unquote(raw: &str) -> String {
+    raw.trim().trim_matches(|c| c == '"' || c == '\'').to_string()
+}
+
+/// Walks `contents` for `require(...)` calls, returning every one that
+/// resolves to a literal path plus every call shaped like a `require` that
+/// doesn't (so callers can diagnose it instead of silently dropping it).
+pub fn parse_file(contents: &str) -> Result<(Vec<RequireCall>, Vec<MalformedRequire>), Box<dyn Error>> {
+    let ast = full_moon::parse(contents).map_err(|errors| {
+        errors
+            .into_iter()
+            .map(|error| error.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    })?;
+
+    let mut visitor = RequireVisitor::default();
+    visitor.visit_ast(&ast);
+
+    Ok((visitor.calls, visitor.malformed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_no_parens_string_call() {
+        let (calls, malformed) = parse_file("require \"foo.lua\"").unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].path, "foo.lua");
+        assert!(malformed.is_empty());
+    }
 
-    Ok(calls)
-}
\ No newline at end of file
+    #[test]
+    fn finds_call_split_across_lines() {
+        let (calls, malformed) = parse_file("require(\n    \"foo.lua\"\n)").unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].path, "foo.lua");
+        assert!(malformed.is_empty());
+    }
+
+    #[test]
+    fn ignores_require_used_as_a_method_or_field() {
+        let (calls, malformed) = parse_file("local obj = {}\nobj.require(\"foo.lua\")\nobj:require(\"foo.lua\")").unwrap();
+        assert!(calls.is_empty());
+        assert!(malformed.is_empty());
+    }
+
+    #[test]
+    fn ignores_commented_out_require() {
+        let (calls, malformed) = parse_file("-- require(\"foo.lua\")\nreturn 1").unwrap();
+        assert!(calls.is_empty());
+        assert!(malformed.is_empty());
+    }
+
+    #[test]
+    fn flags_non_string_argument_as_malformed() {
+        let (calls, malformed) = parse_file("local x = \"foo.lua\"\nrequire(x)").unwrap();
+        assert!(calls.is_empty());
+        assert_eq!(malformed.len(), 1);
+    }
+
+    #[test]
+    fn flags_concatenated_string_as_malformed() {
+        let (calls, malformed) = parse_file("require(\"foo\" .. \".lua\")").unwrap();
+        assert!(calls.is_empty());
+        assert_eq!(malformed.len(), 1);
+    }
+
+    #[test]
+    fn captures_extra_args_after_the_path() {
+        let (calls, _) = parse_file("require(\"foo.lua\", a, b)").unwrap();
+        assert_eq!(calls[0].extra_args, "a, b");
+    }
+}
@@ -0,0 +1,167 @@
+use std::{error::Error, fs, path::Path};
+
+/// A contiguous range of bundled output lines that came from a single source file.
+///
+/// `breakpoints` are module-relative `(line, offset)` pairs (1-based, sorted
+/// ascending by line) recording where splicing a require call shrank the
+/// body, so a line after such a splice can still be mapped back to its real
+/// line in the original file instead of assuming a constant 1:1 offset.
+#[derive(Debug, Clone)]
+pub struct SourceRange {
+    pub output_start: usize,
+    pub output_end: usize,
+    pub source_path: String,
+    pub breakpoints: Vec<(usize, usize)>,
+}
+
+/// Links bundled output line numbers back to the original file and line they
+/// came from, so a runtime error in the bundle can be traced back to the
+/// user's own source instead of an opaque line in the concatenated output.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    ranges: Vec<SourceRange>,
+}
+
+impl SourceMap {
+    pub fn new(ranges: Vec<SourceRange>) -> Self {
+        Self { ranges }
+    }
+
+    /// Resolves a 1-based bundled output line back to `(source_path, original_line)`.
+    pub fn resolve(&self, output_line: usize) -> Option<(&str, usize)> {
+        self.ranges
+            .iter()
+            .find(|range| output_line >= range.output_start && output_line <= range.output_end)
+            .map(|range| {
+                let relative = output_line - range.output_start + 1;
+                let offset = range
+                    .breakpoints
+                    .iter()
+                    .rev()
+                    .find(|(line, _)| *line <= relative)
+                    .map(|(_, offset)| *offset)
+                    .unwrap_or(0);
+
+                (range.source_path.as_str(), relative + offset)
+            })
+    }
+
+    /// Writes the map to `path`, one `start-end -> source_path@line=offset;...` entry per range.
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut contents = String::new();
+
+        for range in &self.ranges {
+            let breakpoints = range.breakpoints.iter().map(|(line, offset)| format!("{}={}", line, offset)).collect::<Vec<_>>().join(";");
+            contents.push_str(&format!("{}-{} -> {}@{}\n", range.output_start, range.output_end, range.source_path, breakpoints));
+        }
+
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    /// Parses a map file previously written by [`SourceMap::write`].
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        let mut ranges = Vec::new();
+
+        for line in contents.lines() {
+            let Some((output, rest)) = line.split_once(" -> ") else {
+                continue;
+            };
+            let Some((start, end)) = output.split_once('-') else {
+                continue;
+            };
+            let Some((source_path, breakpoints)) = rest.rsplit_once('@') else {
+                continue;
+            };
+
+            let mut parsed_breakpoints = Vec::new();
+            for entry in breakpoints.split(';').filter(|entry| !entry.is_empty()) {
+                let Some((bp_line, bp_offset)) = entry.split_once('=') else {
+                    continue;
+                };
+                parsed_breakpoints.push((bp_line.parse()?, bp_offset.parse()?));
+            }
+
+            ranges.push(SourceRange {
+                output_start: start.parse()?,
+                output_end: end.parse()?,
+                source_path: source_path.to_string(),
+                breakpoints: parsed_breakpoints,
+            });
+        }
+
+        Ok(Self { ranges })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn scratch_path(name: &str) -> std::path::PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        std::env::temp_dir().join(format!("luabundler-test-sourcemap-{}-{}", name, COUNTER.fetch_add(1, Ordering::Relaxed)))
+    }
+
+    #[test]
+    fn resolves_lines_before_the_first_breakpoint_1to1() {
+        let map = SourceMap::new(vec![SourceRange {
+            output_start: 5,
+            output_end: 20,
+            source_path: "a.lua".to_string(),
+            breakpoints: vec![(4, 2)],
+        }]);
+
+        // Relative line 3 (output line 7) is before the breakpoint at relative line 4, so offset is 0.
+        assert_eq!(map.resolve(7), Some(("a.lua", 3)));
+    }
+
+    #[test]
+    fn resolves_lines_after_a_breakpoint_with_its_offset() {
+        let map = SourceMap::new(vec![SourceRange {
+            output_start: 5,
+            output_end: 20,
+            source_path: "a.lua".to_string(),
+            breakpoints: vec![(4, 2)],
+        }]);
+
+        // Relative line 4 (output line 8) is at/after the breakpoint, so it picks up offset 2.
+        assert_eq!(map.resolve(8), Some(("a.lua", 6)));
+    }
+
+    #[test]
+    fn resolves_using_the_latest_applicable_breakpoint() {
+        let map = SourceMap::new(vec![SourceRange {
+            output_start: 1,
+            output_end: 100,
+            source_path: "a.lua".to_string(),
+            breakpoints: vec![(4, 2), (10, 5)],
+        }]);
+
+        assert_eq!(map.resolve(12), Some(("a.lua", 17))); // relative 12 + offset 5
+    }
+
+    #[test]
+    fn resolve_returns_none_outside_every_range() {
+        let map = SourceMap::new(vec![SourceRange { output_start: 5, output_end: 10, source_path: "a.lua".to_string(), breakpoints: vec![] }]);
+        assert_eq!(map.resolve(1), None);
+        assert_eq!(map.resolve(11), None);
+    }
+
+    #[test]
+    fn write_then_load_round_trips() {
+        let path = scratch_path("roundtrip");
+        let map = SourceMap::new(vec![
+            SourceRange { output_start: 1, output_end: 3, source_path: "a.lua".to_string(), breakpoints: vec![] },
+            SourceRange { output_start: 4, output_end: 9, source_path: "b.lua".to_string(), breakpoints: vec![(2, 1), (5, 3)] },
+        ]);
+
+        map.write(&path).unwrap();
+        let loaded = SourceMap::load(&path).unwrap();
+
+        assert_eq!(map.resolve(2), loaded.resolve(2));
+        assert_eq!(map.resolve(8), loaded.resolve(8));
+    }
+}
@@ -0,0 +1,177 @@
+use colored::Colorize;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::HashMap,
+    error::Error,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+use tokio::time::Instant;
+
+use crate::code_processing::{emit_bundle, process_code};
+use crate::dependency_graph::{canonical_path, compute_line_breakpoints, DependencyGraph};
+use crate::file_processing::write_in_chunks;
+
+fn hash_contents(contents: &str) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn watcher_channel() -> Result<(RecommendedWatcher, tokio::sync::mpsc::UnboundedReceiver<notify::Event>), Box<dyn Error>> {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            let _ = tx.send(event);
+        }
+    })?;
+
+    Ok((watcher, rx))
+}
+
+// Rebuilds the bundle from scratch and returns each module's canonical path
+// hashed by its on-disk content, so later events can tell a genuine edit from a no-op save.
+async fn rebuild(main_path: &str, bundle_path: &str, minify: bool, noprocess: bool) -> Result<(DependencyGraph, HashMap<String, u64>), Box<dyn Error>> {
+    let start = Instant::now();
+
+    let graph = DependencyGraph::build(Path::new(main_path)).await?;
+    let hashes = graph
+        .order()
+        .iter()
+        .map(|path| (path.clone(), hash_contents(&std::fs::read_to_string(path).unwrap_or_default())))
+        .collect();
+
+    let (bundled, source_map) = emit_bundle(&graph, minify);
+    write_in_chunks(bundle_path, bundled.as_bytes(), 1024 * 1024).await?;
+    source_map.write(Path::new(&format!("{}.map", bundle_path)))?;
+
+    println!("{}", format!("{} {} {}", "Bundled".blue(), main_path, format!("in {:?}", start.elapsed()).dimmed()));
+
+    if !noprocess {
+        let start = Instant::now();
+        process_code(PathBuf::from(bundle_path), minify);
+        println!("{}", format!("{} {} {}", "Processed".blue(), bundle_path, format!("in {:?}", start.elapsed()).dimmed()));
+    }
+
+    Ok((graph, hashes))
+}
+
+// Re-splices only `path` using the cached graph, without re-running the DFS.
+// Only safe when `path`'s resolved requires are unchanged from the last build.
+// `breakpoints` must be recomputed by the caller even though the require set
+// is unchanged, since an edit can still shift the module's internal line
+// numbers (e.g. a comment added above a require call).
+async fn patch(graph: &mut DependencyGraph, bundle_path: &str, minify: bool, noprocess: bool, path: &str, contents: &str, breakpoints: Vec<(usize, usize)>) -> Result<(), Box<dyn Error>> {
+    graph.patch_source(path, contents.to_string());
+    graph.patch_line_map(path, breakpoints);
+
+    let (bundled, source_map) = emit_bundle(graph, minify);
+    write_in_chunks(bundle_path, bundled.as_bytes(), 1024 * 1024).await?;
+    source_map.write(Path::new(&format!("{}.map", bundle_path)))?;
+
+    if !noprocess {
+        process_code(PathBuf::from(bundle_path), minify);
+    }
+
+    Ok(())
+}
+
+/// Keeps rebundling `main_path` whenever a file in its dependency graph
+/// changes on disk. Rebuilds are skipped when the changed file's content hash
+/// is unchanged (debouncing editor "save" storms that rewrite identical
+/// bytes), and when a file's require set is unchanged from the last build,
+/// only that module is re-spliced instead of rebuilding the whole graph.
+pub async fn watch(main_path: &str, bundle_path: &str, minify: bool, noprocess: bool) -> Result<(), Box<dyn Error>> {
+    let (mut graph, mut hashes) = rebuild(main_path, bundle_path, minify, noprocess).await?;
+
+    let (mut watcher, mut rx) = watcher_channel()?;
+    let watch_root = Path::new(main_path)
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    watcher.watch(watch_root, RecursiveMode::Recursive)?;
+
+    println!("{}", format!("{} {}", "Watching".blue(), main_path).dimmed());
+
+    while let Some(event) = rx.recv().await {
+        if !matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_)) {
+            continue;
+        }
+
+        for changed_path in &event.paths {
+            let key = canonical_path(changed_path);
+            let Some(&old_hash) = hashes.get(&key) else {
+                continue;
+            };
+
+            let Ok(contents) = tokio::fs::read_to_string(changed_path).await else {
+                continue;
+            };
+
+            let new_hash = hash_contents(&contents);
+            if new_hash == old_hash {
+                continue;
+            }
+
+            let rebuild_result = match crate::require_parser::parse_file(&contents) {
+                // A malformed require needs the same diagnostic `rebuild` (via
+                // `DependencyGraph::build`) would raise, so fall back to it
+                // rather than silently patching around the bad call.
+                Ok((_, malformed)) if !malformed.is_empty() => rebuild(main_path, bundle_path, minify, noprocess).await.map(Some),
+                Ok((calls, _malformed)) => {
+                    let new_requires: Vec<String> = calls
+                        .iter()
+                        .map(|call| canonical_path(&changed_path.parent().unwrap_or_else(|| Path::new("")).join(&call.path)))
+                        .collect();
+
+                    if graph.requires(&key) == Some(new_requires.as_slice()) {
+                        let breakpoints = compute_line_breakpoints(&contents, &calls);
+                        let mut rewritten = contents.clone();
+                        for (call, dep_key) in calls.into_iter().rev().zip(new_requires.iter().rev()) {
+                            let replacement = if call.extra_args.is_empty() {
+                                format!("__require(\"{}\")", dep_key)
+                            } else {
+                                format!("__require(\"{}\", {})", dep_key, call.extra_args)
+                            };
+                            rewritten.replace_range(call.span, &replacement);
+                        }
+
+                        patch(&mut graph, bundle_path, minify, noprocess, &key, &rewritten, breakpoints).await.map(|_| None)
+                    } else {
+                        rebuild(main_path, bundle_path, minify, noprocess).await.map(Some)
+                    }
+                }
+                Err(_) => rebuild(main_path, bundle_path, minify, noprocess).await.map(Some),
+            };
+
+            match rebuild_result {
+                Ok(Some((new_graph, new_hashes))) => {
+                    graph = new_graph;
+                    hashes = new_hashes;
+                }
+                Ok(None) => {
+                    hashes.insert(key, new_hash);
+                }
+                Err(err) => eprintln!("{}", format!("rebuild failed: {}", err).red()),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_contents_hash_the_same() {
+        let contents = "return require(\"b.lua\")";
+        assert_eq!(hash_contents(contents), hash_contents(contents));
+    }
+
+    #[test]
+    fn different_contents_hash_differently() {
+        assert_ne!(hash_contents("return 1"), hash_contents("return 2"));
+    }
+}